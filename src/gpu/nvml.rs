@@ -0,0 +1,75 @@
+//! Optional NVML-backed device info, enabled with the `nvml` feature. When compiled in and
+//! an NVIDIA platform is detected, [`device_info_by_pci_bus_id`] reports the true SM/core
+//! count, current clocks, and live free/used memory straight from the driver, which is far
+//! more accurate than the static `CORE_COUNTS` table and OpenCL's coarse `GlobalMemSize`.
+//! NVML devices are matched to OpenCL devices by PCI bus id so the right handle is used on
+//! multi-GPU hosts. Callers should fall back to the existing OpenCL-only path whenever this
+//! returns `None`, e.g. because NVML isn't installed or the bus id has no match.
+
+use log::warn;
+use nvml_wrapper::enum_wrappers::device::Clock;
+use nvml_wrapper::Nvml;
+
+lazy_static::lazy_static! {
+    static ref NVML: Option<Nvml> = match Nvml::init() {
+        Ok(nvml) => Some(nvml),
+        Err(e) => {
+            warn!("nvml feature is enabled but NVML could not be initialized: {}", e);
+            None
+        }
+    };
+}
+
+/// Device facts read live from NVML, to be preferred over the static OpenCL-derived ones.
+pub struct NvmlDeviceInfo {
+    pub core_count: usize,
+    pub core_clock_mhz: Option<u32>,
+    pub memory_clock_mhz: Option<u32>,
+    pub memory_bus_width: Option<u32>,
+    pub total_memory: u64,
+    pub free_memory: u64,
+}
+
+/// Look up live device info from NVML for the OpenCL device with the given PCI bus id, as
+/// reported by the `CL_DEVICE_PCI_BUS_ID_NV` extension (e.g. `"0000:01:00.0"`). Returns
+/// `None` when NVML is unavailable or no matching device is found.
+pub fn device_info_by_pci_bus_id(pci_bus_id: &str) -> Option<NvmlDeviceInfo> {
+    let nvml = NVML.as_ref()?;
+    let device = match nvml.device_by_pci_bus_id(pci_bus_id) {
+        Ok(device) => device,
+        Err(e) => {
+            warn!(
+                "Could not find NVML device for PCI bus id {}: {}",
+                pci_bus_id, e
+            );
+            return None;
+        }
+    };
+
+    let core_count = match device.num_cores() {
+        Ok(cores) => cores as usize,
+        Err(e) => {
+            warn!("Could not read NVML core count for {}: {}", pci_bus_id, e);
+            return None;
+        }
+    };
+    let memory = match device.memory_info() {
+        Ok(memory) => memory,
+        Err(e) => {
+            warn!("Could not read NVML memory info for {}: {}", pci_bus_id, e);
+            return None;
+        }
+    };
+    let core_clock_mhz = device.clock_info(Clock::SM).ok();
+    let memory_clock_mhz = device.clock_info(Clock::Memory).ok();
+    let memory_bus_width = device.memory_bus_width().ok();
+
+    Some(NvmlDeviceInfo {
+        core_count,
+        core_clock_mhz,
+        memory_clock_mhz,
+        memory_bus_width,
+        total_memory: memory.total,
+        free_memory: memory.free,
+    })
+}
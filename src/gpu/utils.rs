@@ -1,10 +1,34 @@
 use crate::gpu::error::{GPUError, GPUResult};
+use ocl::core::ClDeviceIdPtr;
 use ocl::{Device, Platform};
 
 use log::{info, warn};
 use std::collections::HashMap;
 use std::env;
 
+#[cfg(feature = "nvml")]
+use crate::gpu::nvml;
+
+// `CL_DEVICE_PCI_BUS_ID_NV` (0x4008) is an NVIDIA vendor extension (`cl_nv_device_attribute_query`)
+// that most OpenCL wrapper crates, including the typed `ocl::enums::DeviceInfo` we use
+// elsewhere in this file, don't expose directly. Query it as a raw attribute via
+// `Device::info_raw`, which every `ocl` version supports for non-standard extension codes,
+// so this degrades to `None` (rather than failing to compile) on platforms/devices that
+// don't support it. This gives every device a stable identity that's unique per physical
+// card, which we use both to deduplicate devices reported through more than one OpenCL ICD
+// (`get_all_devices`) and, with the `nvml` feature, to match an OpenCL device to its NVML
+// handle.
+const CL_DEVICE_PCI_BUS_ID_NV: u32 = 0x4008;
+
+fn pci_bus_id(d: &Device) -> Option<String> {
+    let bytes = d.info_raw(CL_DEVICE_PCI_BUS_ID_NV).ok()?;
+    if bytes.len() < 4 {
+        return None;
+    }
+    let bus_id = u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    Some(format!("0000:{:02x}:00.0", bus_id))
+}
+
 pub const GPU_NVIDIA_PLATFORM_NAME: &str = "NVIDIA CUDA";
 pub const GPU_AMD_PLATFORM_NAME: &str = "AMD Accelerated Parallel Processing";
 //pub const CPU_INTEL_PLATFORM_NAME: &str = "Intel(R) CPU Runtime for OpenCL(TM) Applications";
@@ -45,11 +69,319 @@ pub fn get_platform(platform_name: Option<&str>) -> GPUResult<Platform> {
     find_platform(&platform_name.unwrap())
 }
 
+// `Device::list_all` also returns CPU and custom OpenCL devices on many stacks, which we
+// don't want to silently try to "GPU-accelerate" on. Keep GPUs and accelerators by
+// default, and let `BELLMAN_ALLOW_OPENCL_CPU` opt back in to CPU devices for OpenCL
+// implementations that actually benefit from them.
+fn wanted_device_types() -> ocl::flags::DeviceType {
+    let mut types = ocl::flags::DeviceType::GPU | ocl::flags::DeviceType::ACCELERATOR;
+    if env::var("BELLMAN_ALLOW_OPENCL_CPU").is_ok() {
+        types |= ocl::flags::DeviceType::CPU;
+    }
+    types
+}
+
+fn filter_devices_by_type(devices: Vec<Device>) -> Vec<Device> {
+    let wanted = wanted_device_types();
+    devices
+        .into_iter()
+        .filter(|d| match d.info(ocl::enums::DeviceInfo::Type) {
+            Ok(ocl::enums::DeviceInfoResult::Type(ty)) => {
+                if ty.intersects(wanted) {
+                    true
+                } else {
+                    info!(
+                        "Filtering out device \"{}\" ({:?}), not in the allowed device types",
+                        d.name().unwrap_or_default(),
+                        ty
+                    );
+                    false
+                }
+            }
+            _ => true,
+        })
+        .collect()
+}
+
+// Restrict and order the enumerated devices according to `BELLMAN_VISIBLE_DEVICES`, a
+// comma-separated list of indices into `entries` (analogous to CUDA_VISIBLE_DEVICES). When
+// unset, default to sorting by descending core count so the strongest GPU is picked first.
+// Entries carry their core count alongside the device so callers compute it once (via
+// `get_core_count`) rather than this function re-deriving it for every device on every call.
+fn order_devices(
+    entries: Vec<(Platform, Device, usize)>,
+) -> GPUResult<Vec<(Platform, Device, usize)>> {
+    match env::var("BELLMAN_VISIBLE_DEVICES") {
+        Ok(indices) => select_visible_devices(entries, &indices),
+        Err(_) => {
+            let mut entries = entries;
+            entries.sort_by_key(|(_, _, core_count)| std::cmp::Reverse(*core_count));
+            Ok(entries)
+        }
+    }
+}
+
+// Parses `BELLMAN_VISIBLE_DEVICES` into the resolved, in-order list of indices it selects,
+// rejecting duplicates and indices outside `[0, len)`. Pulled out of `select_visible_devices`
+// so the index-validation logic can be unit tested without needing real OpenCL devices.
+fn resolve_visible_indices(len: usize, indices: &str) -> GPUResult<Vec<usize>> {
+    let mut seen = std::collections::HashSet::new();
+    let mut resolved = Vec::new();
+    for part in indices.split(',') {
+        let part = part.trim();
+        if part.is_empty() {
+            continue;
+        }
+        let index: usize = part
+            .parse()
+            .map_err(|_| GPUError::Simple("Invalid index in BELLMAN_VISIBLE_DEVICES!"))?;
+        if !seen.insert(index) {
+            return Err(GPUError::Simple(
+                "Duplicate index in BELLMAN_VISIBLE_DEVICES!",
+            ));
+        }
+        if index >= len {
+            return Err(GPUError::Simple(
+                "Index in BELLMAN_VISIBLE_DEVICES is out of range!",
+            ));
+        }
+        resolved.push(index);
+    }
+    Ok(resolved)
+}
+
+fn select_visible_devices(
+    entries: Vec<(Platform, Device, usize)>,
+    indices: &str,
+) -> GPUResult<Vec<(Platform, Device, usize)>> {
+    let order = resolve_visible_indices(entries.len(), indices)?;
+    let mut entries: Vec<Option<(Platform, Device, usize)>> =
+        entries.into_iter().map(Some).collect();
+    Ok(order
+        .into_iter()
+        .map(|index| entries[index].take().expect("index already validated"))
+        .collect())
+}
+
+#[test]
+fn test_resolve_visible_indices() {
+    assert_eq!(resolve_visible_indices(4, "2,0").unwrap(), vec![2, 0]);
+    assert_eq!(resolve_visible_indices(4, "").unwrap(), Vec::<usize>::new());
+    assert_eq!(resolve_visible_indices(4, " 1 , 3 ").unwrap(), vec![1, 3]);
+
+    match resolve_visible_indices(4, "1,1") {
+        Err(GPUError::Simple(msg)) => assert!(msg.contains("Duplicate")),
+        other => panic!("expected a duplicate-index error, got {:?}", other),
+    }
+
+    match resolve_visible_indices(4, "4") {
+        Err(GPUError::Simple(msg)) => assert!(msg.contains("out of range")),
+        other => panic!("expected an out-of-range error, got {:?}", other),
+    }
+
+    match resolve_visible_indices(4, "abc") {
+        Err(GPUError::Simple(msg)) => assert!(msg.contains("Invalid")),
+        other => panic!("expected an invalid-index error, got {:?}", other),
+    }
+}
+
 pub fn get_devices(platform: &Platform) -> GPUResult<Vec<Device>> {
     if env::var("BELLMAN_NO_GPU").is_ok() {
         return Err(GPUError::Simple("GPU accelerator is disabled!"));
     }
-    Ok(Device::list_all(platform)?)
+    let devices = filter_devices_by_type(Device::list_all(platform)?);
+    let entries = devices
+        .into_iter()
+        .map(|d| {
+            let core_count = get_core_count(platform, d).unwrap_or(0);
+            (*platform, d, core_count)
+        })
+        .collect();
+    let entries = order_devices(entries)?;
+    Ok(entries.into_iter().map(|(_, d, _)| d).collect())
+}
+
+// A per-device identity used to deduplicate `get_all_devices`'s output. When the PCI bus id
+// extension is readable (NVIDIA today), it's used directly since it's unique per physical
+// card and stable across however many OpenCL ICDs re-expose that card. Otherwise fall back
+// to the device's own pointer, which OpenCL hands out uniquely per device within a single
+// enumeration — unlike name+compute-units, it won't collapse two identical cards (e.g. a
+// rig with several identical RTX 3090s) into one.
+fn device_identity(platform_name: &str, device: &Device) -> String {
+    match pci_bus_id(device) {
+        Some(bus_id) => bus_id,
+        None => format!("{}:{:?}", platform_name, device.as_ptr()),
+    }
+}
+
+/// Collects GPU devices from every OpenCL platform found on the host, so a machine with
+/// e.g. both an NVIDIA and an AMD card can drive both. Some OpenCL stacks report the same
+/// physical device through more than one platform/ICD, so entries are deduplicated by PCI
+/// bus id (or, failing that, by device identity) before being returned — this intentionally
+/// does not key on device name, which would wrongly collapse multiple identical GPUs (e.g.
+/// several identical RTX 3090s) into one. The result honors `BELLMAN_VISIBLE_DEVICES`,
+/// defaulting to descending core-count order, same as `get_devices`.
+pub fn get_all_devices() -> GPUResult<Vec<GpuDevice>> {
+    if env::var("BELLMAN_NO_GPU").is_ok() {
+        return Err(GPUError::Simple("GPU accelerator is disabled!"));
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    let mut entries = Vec::new();
+    for platform in Platform::list()? {
+        let platform_name = platform.name().unwrap_or_default();
+        for device in filter_devices_by_type(Device::list_all(platform)?).into_iter() {
+            let identity = device_identity(&platform_name, &device);
+            if seen.insert(identity) {
+                let core_count = get_core_count(&platform, device).unwrap_or(0);
+                entries.push((platform, device, core_count));
+            } else {
+                info!(
+                    "Skipping duplicate device \"{}\" reported again on platform \"{}\"",
+                    device.name().unwrap_or_default(),
+                    platform_name
+                );
+            }
+        }
+    }
+
+    order_devices(entries)?
+        .into_iter()
+        .map(|(platform, device, core_count)| GpuDevice::new(platform, device, core_count))
+        .collect()
+}
+
+/// A structured view of an OpenCL GPU, bundling the underlying `ocl::Device` together with
+/// its owning `Platform` and the facts bellman cares about for planning kernel launches
+/// (core count, memory, compute units, and clocks/bus width where the platform exposes
+/// them), so downstream FFT/multiexp code doesn't have to re-query OpenCL ad hoc.
+#[derive(Clone, Debug)]
+pub struct GpuDevice {
+    device: Device,
+    platform: Platform,
+    name: String,
+    core_count: usize,
+    memory: u64,
+    max_compute_units: u32,
+    core_clock_mhz: Option<u32>,
+    memory_clock_mhz: Option<u32>,
+    memory_bus_width: Option<u32>,
+}
+
+impl GpuDevice {
+    // `core_count` is computed by the caller (via `get_core_count`) rather than re-derived
+    // here, so that `get_all_devices` only computes it once per device —
+    // otherwise unlisted GPUs would log their "estimating N cores" message, and (with the
+    // `nvml` feature) make their NVML round-trip, once for sorting and again per device here.
+    fn new(platform: Platform, device: Device, core_count: usize) -> GPUResult<GpuDevice> {
+        let name = device.name()?;
+        let memory = get_memory(&platform, device)?;
+        let max_compute_units = match device.info(ocl::enums::DeviceInfo::MaxComputeUnits) {
+            Ok(ocl::enums::DeviceInfoResult::MaxComputeUnits(units)) => units,
+            _ => 0,
+        };
+        // These are only reassigned by the optional NVML backend below, which is why they're
+        // `mut` at all — without the `nvml` feature they're never mutated again.
+        #[cfg_attr(not(feature = "nvml"), allow(unused_mut))]
+        let mut core_clock_mhz = match device.info(ocl::enums::DeviceInfo::MaxClockFrequency) {
+            Ok(ocl::enums::DeviceInfoResult::MaxClockFrequency(mhz)) => Some(mhz),
+            _ => None,
+        };
+        // Plain OpenCL has no portable query for memory clock or bus width; these stay
+        // `None` unless the optional NVML backend fills them in for NVIDIA platforms.
+        #[cfg_attr(not(feature = "nvml"), allow(unused_mut))]
+        let mut memory_clock_mhz = None;
+        #[cfg_attr(not(feature = "nvml"), allow(unused_mut))]
+        let mut memory_bus_width = None;
+
+        #[cfg(feature = "nvml")]
+        {
+            if platform.name().unwrap_or_default() == GPU_NVIDIA_PLATFORM_NAME {
+                if let Some(info) =
+                    pci_bus_id(&device).and_then(|id| nvml::device_info_by_pci_bus_id(&id))
+                {
+                    core_clock_mhz = info.core_clock_mhz.or(core_clock_mhz);
+                    memory_clock_mhz = info.memory_clock_mhz;
+                    memory_bus_width = info.memory_bus_width;
+                }
+            }
+        }
+
+        Ok(GpuDevice {
+            device,
+            platform,
+            name,
+            core_count,
+            memory,
+            max_compute_units,
+            core_clock_mhz,
+            memory_clock_mhz,
+            memory_bus_width,
+        })
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    pub fn platform(&self) -> &Platform {
+        &self.platform
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn core_count(&self) -> usize {
+        self.core_count
+    }
+
+    pub fn memory(&self) -> u64 {
+        self.memory
+    }
+
+    pub fn max_compute_units(&self) -> u32 {
+        self.max_compute_units
+    }
+
+    pub fn core_clock_mhz(&self) -> Option<u32> {
+        self.core_clock_mhz
+    }
+
+    pub fn memory_clock_mhz(&self) -> Option<u32> {
+        self.memory_clock_mhz
+    }
+
+    pub fn memory_bus_width(&self) -> Option<u32> {
+        self.memory_bus_width
+    }
+
+    /// A global work size covering one work-item per core, rounded up to a multiple of the
+    /// compute unit count so the work divides evenly across the device's SMs/CUs.
+    pub fn recommended_work_size(&self) -> usize {
+        recommended_work_size_for(self.core_count, self.max_compute_units)
+    }
+}
+
+// Pure core of `GpuDevice::recommended_work_size`, pulled out so it can be unit tested
+// without constructing a real `GpuDevice` (which needs a live OpenCL device).
+fn recommended_work_size_for(core_count: usize, max_compute_units: u32) -> usize {
+    let units = max_compute_units as usize;
+    if units == 0 {
+        return core_count;
+    }
+    core_count.div_ceil(units) * units
+}
+
+#[test]
+fn test_recommended_work_size_for() {
+    // Already a multiple of the compute unit count: unchanged.
+    assert_eq!(recommended_work_size_for(4352, 68), 4352);
+    // Not a multiple: rounds up to the next one.
+    assert_eq!(recommended_work_size_for(4350, 68), 4352);
+    assert_eq!(recommended_work_size_for(1, 68), 68);
+    // Unknown compute unit count: falls back to the raw core count.
+    assert_eq!(recommended_work_size_for(2560, 0), 2560);
 }
 
 lazy_static::lazy_static! {
@@ -99,24 +431,142 @@ lazy_static::lazy_static! {
 }
 
 const DEFAULT_CORE_COUNT: usize = 2560;
-pub fn get_core_count(d: Device) -> GPUResult<usize> {
+const MIN_ESTIMATED_CORE_COUNT: usize = 128;
+
+// Consumer-class GeForce Maxwell (GTX 9xx) and Pascal (GTX 10xx) chips, and their Quadro
+// M-series workstation counterparts, pack 128 CUDA cores per SM — confirmed by the known
+// counts already in `CORE_COUNTS` (e.g. GTX 1080 Ti = 28 SMs x 128 = 3584). Turing (RTX
+// 20-series, Quadro RTX, TITAN RTX, and the Turing-based GTX 16xx) and datacenter
+// Volta/Pascal (Tesla V100/P100) pack 64 cores per SM instead (e.g. RTX 2080 Ti = 68 SMs x
+// 64 = 4352). We don't have a reliable way to read the architecture generation over OpenCL,
+// so we guess it from well-known model name fragments and default to 64, since Turing+ is
+// the common case for GPUs not yet in `CORE_COUNTS`.
+fn nvidia_cores_per_compute_unit(device_name: &str) -> usize {
+    const WIDE_SM_MARKERS: &[&str] = &["GTX 9", "GTX 10", "Quadro M"];
+    if WIDE_SM_MARKERS.iter().any(|marker| device_name.contains(marker)) {
+        128
+    } else {
+        64
+    }
+}
+
+fn amd_cores_per_compute_unit() -> usize {
+    64
+}
+
+// Estimate a CUDA/stream core count for a device that isn't in `CORE_COUNTS`, derived
+// from its reported compute unit count and a vendor-specific lanes-per-CU factor. This
+// mirrors how rust-gpu-tools estimates a core count for non-CUDA cards.
+fn estimate_core_count(platform_name: &str, device_name: &str, max_compute_units: u32) -> usize {
+    let cores_per_unit = if platform_name == GPU_NVIDIA_PLATFORM_NAME {
+        nvidia_cores_per_compute_unit(device_name)
+    } else {
+        amd_cores_per_compute_unit()
+    };
+
+    ((max_compute_units as usize) * cores_per_unit).max(MIN_ESTIMATED_CORE_COUNT)
+}
+
+#[test]
+fn test_nvidia_cores_per_compute_unit() {
+    // Consumer Maxwell/Pascal: 128 cores/SM.
+    assert_eq!(nvidia_cores_per_compute_unit("GeForce GTX 1080 Ti"), 128);
+    assert_eq!(nvidia_cores_per_compute_unit("GeForce GTX 1060"), 128);
+    assert_eq!(nvidia_cores_per_compute_unit("Quadro M5000"), 128);
+    // Turing and datacenter Volta/Pascal: 64 cores/SM.
+    assert_eq!(nvidia_cores_per_compute_unit("GeForce RTX 2080 Ti"), 64);
+    assert_eq!(nvidia_cores_per_compute_unit("GeForce GTX 1660 Ti"), 64);
+    assert_eq!(nvidia_cores_per_compute_unit("Quadro RTX 6000"), 64);
+    assert_eq!(nvidia_cores_per_compute_unit("Tesla V100"), 64);
+    // Unknown/future architectures default to the Turing+ factor.
+    assert_eq!(nvidia_cores_per_compute_unit("GeForce RTX 4090"), 64);
+}
+
+#[test]
+fn test_estimate_core_count_matches_core_counts_table() {
+    // GeForce GTX 1080 Ti: 28 SMs x 128 cores/SM = 3584, matching `CORE_COUNTS`.
+    assert_eq!(
+        estimate_core_count(GPU_NVIDIA_PLATFORM_NAME, "GeForce GTX 1080 Ti", 28),
+        3584
+    );
+    // GeForce RTX 2080 Ti: 68 SMs x 64 cores/SM = 4352, matching `CORE_COUNTS`.
+    assert_eq!(
+        estimate_core_count(GPU_NVIDIA_PLATFORM_NAME, "GeForce RTX 2080 Ti", 68),
+        4352
+    );
+    // AMD GCN/RDNA: 64 shader cores per compute unit.
+    assert_eq!(
+        estimate_core_count(GPU_AMD_PLATFORM_NAME, "gfx1010", 40),
+        2560
+    );
+    // The estimate never drops below the sane minimum, even for a tiny compute unit count.
+    assert_eq!(
+        estimate_core_count(GPU_NVIDIA_PLATFORM_NAME, "GeForce RTX 2080 Ti", 1),
+        MIN_ESTIMATED_CORE_COUNT
+    );
+}
+
+pub fn get_core_count(platform: &Platform, d: Device) -> GPUResult<usize> {
     let name = d.name()?;
+
+    #[cfg(feature = "nvml")]
+    {
+        if platform.name().unwrap_or_default() == GPU_NVIDIA_PLATFORM_NAME {
+            if let Some(info) = pci_bus_id(&d).and_then(|id| nvml::device_info_by_pci_bus_id(&id))
+            {
+                info!(
+                    "Using NVML-reported core count for \"{}\": {} cores",
+                    name, info.core_count
+                );
+                return Ok(info.core_count);
+            }
+        }
+    }
+
     match CORE_COUNTS.get(&name[..]) {
         Some(&cores) => Ok(cores),
-        None => {
-            warn!(
-                "Number of CUDA cores for your device ({}) is unknown! Best performance is \
-                 only achieved when the number of CUDA cores is known! You can find the \
-                 instructions on how to support custom GPUs here: \
-                 https://lotu.sh/en+hardware-mining",
-                name
-            );
-            Ok(DEFAULT_CORE_COUNT)
-        }
+        None => match d.info(ocl::enums::DeviceInfo::MaxComputeUnits) {
+            Ok(ocl::enums::DeviceInfoResult::MaxComputeUnits(units)) => {
+                let platform_name = platform.name().unwrap_or_default();
+                let estimated = estimate_core_count(&platform_name, &name, units);
+                info!(
+                    "Number of CUDA cores for your device ({}) is unknown, estimating {} cores \
+                     from {} compute units reported on \"{}\". Best performance is only \
+                     achieved when the exact number of CUDA cores is known! You can find the \
+                     instructions on how to support custom GPUs here: \
+                     https://lotu.sh/en+hardware-mining",
+                    name, estimated, units, platform_name
+                );
+                Ok(estimated)
+            }
+            _ => {
+                warn!(
+                    "Number of CUDA cores for your device ({}) is unknown and its compute unit \
+                     count could not be read! Best performance is only achieved when the \
+                     number of CUDA cores is known! You can find the instructions on how to \
+                     support custom GPUs here: https://lotu.sh/en+hardware-mining",
+                    name
+                );
+                Ok(DEFAULT_CORE_COUNT)
+            }
+        },
     }
 }
 
-pub fn get_memory(d: Device) -> GPUResult<u64> {
+// `platform` is only read by the NVML fast path below; it's unused without the `nvml`
+// feature.
+#[cfg_attr(not(feature = "nvml"), allow(unused_variables))]
+pub fn get_memory(platform: &Platform, d: Device) -> GPUResult<u64> {
+    #[cfg(feature = "nvml")]
+    {
+        if platform.name().unwrap_or_default() == GPU_NVIDIA_PLATFORM_NAME {
+            if let Some(info) = pci_bus_id(&d).and_then(|id| nvml::device_info_by_pci_bus_id(&id))
+            {
+                return Ok(info.free_memory);
+            }
+        }
+    }
+
     match d.info(ocl::enums::DeviceInfo::GlobalMemSize)? {
         ocl::enums::DeviceInfoResult::GlobalMemSize(sz) => Ok(sz),
         _ => Err(GPUError::Simple("Cannot extract GPU memory!")),